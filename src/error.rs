@@ -8,7 +8,95 @@ use grammar;
 /// The number of lines to display as error context.
 const ERROR_CONTEXT_LINES: usize = 5;
 
+/// Delimiter pairs the grammar can be left waiting to close at
+/// end of input: template, link and table syntax.
+const DELIMITER_PAIRS: [(&str, &str); 3] = [("{{", "}}"), ("[[", "]]"), ("{|", "|}")];
 
+/// Returns the closing token for a known opening delimiter.
+fn closer_for(open: &str) -> Option<&'static str> {
+    DELIMITER_PAIRS.iter()
+        .find(|&&(o, _)| o == open)
+        .map(|&(_, c)| c)
+}
+
+/// Maps a raw `expected` token straight from the PEG's `err.expected` to
+/// a human-readable description, so `Display` doesn't leak grammar
+/// literals or internal rule names verbatim.
+fn friendly_expected(token: &str) -> String {
+    if util::is_whitespace(token) {
+        return "whitespace".to_string();
+    }
+    match token {
+        "{{" => "template open".to_string(),
+        "}}" => "template close".to_string(),
+        "[[" => "link open".to_string(),
+        "]]" => "link close".to_string(),
+        "{|" => "table open".to_string(),
+        "|}" => "table close".to_string(),
+        "EOF" | "eof" => "end of input".to_string(),
+        other => format!("`{}`", other),
+    }
+}
+
+/// Deduplicates and sorts a raw `expected` token set, mapping each token
+/// through `friendly_expected` for a tidy, stable `Display` output.
+fn normalize_expected(expected: &[String]) -> Vec<String> {
+    let mut cleaned: Vec<String> = expected.iter()
+        .map(|token| friendly_expected(token))
+        .collect();
+    cleaned.sort();
+    cleaned.dedup();
+    cleaned
+}
+
+/// Scans `input[..offset]` backward from a parse failure, tracking a
+/// stack of closing delimiters seen so far. An opener is "matched" as
+/// soon as its closer has already been seen further to the right;
+/// otherwise it is the innermost construct still waiting to be closed,
+/// and its position is returned immediately, since scanning backward
+/// means the first unmatched opener found is the closest one to the
+/// error.
+fn find_unclosed_delimiter(input: &str, offset: usize) -> Option<(usize, String)> {
+
+    // Scan over bytes, not `str` slices: all delimiter tokens are ASCII,
+    // but the wikitext around them routinely is not, and indexing a
+    // `str` at a non-char-boundary byte offset panics.
+    let haystack = &input.as_bytes()[..offset.min(input.len())];
+    let mut pending_closers: Vec<&str> = vec![];
+    let mut i = haystack.len();
+
+    while i > 0 {
+        let mut matched_here = false;
+
+        for &(open, close) in DELIMITER_PAIRS.iter() {
+            let close_bytes = close.as_bytes();
+            let open_bytes = open.as_bytes();
+
+            if i >= close_bytes.len() && &haystack[i - close_bytes.len()..i] == close_bytes {
+                pending_closers.push(close);
+                i -= close_bytes.len();
+                matched_here = true;
+                break;
+            }
+            if i >= open_bytes.len() && &haystack[i - open_bytes.len()..i] == open_bytes {
+                if let Some(pos) = closer_for(open)
+                    .and_then(|c| pending_closers.iter().rposition(|&seen| seen == c)) {
+                    pending_closers.remove(pos);
+                } else {
+                    return Some((i - open_bytes.len(), open.to_string()));
+                }
+                i -= open_bytes.len();
+                matched_here = true;
+                break;
+            }
+        }
+
+        if !matched_here {
+            i -= 1;
+        }
+    }
+    None
+}
 
 /// The parser error with source code context.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -19,6 +107,12 @@ pub struct ParseError {
     pub context: Vec<String>,
     pub context_start: usize,
     pub context_end: usize,
+
+    /// If the error is an end-of-input failure caused by an unclosed
+    /// template, link or table, the position of the opening token that
+    /// was never closed and the token itself (e.g. `"{{"`).
+    #[serde(default)]
+    pub unclosed: Option<(ast::Position, String)>,
 }
 
 /// Error structure for syntax tree transformations.
@@ -66,12 +160,24 @@ impl ParseError {
             context.push(String::from(sloc.content));
         }
 
+        let at_eof = input[err.offset..].trim().is_empty();
+        let expects_closer = token_str.iter()
+            .any(|t| DELIMITER_PAIRS.iter().any(|&(_, close)| t == close));
+
+        let unclosed = if at_eof && expects_closer {
+            find_unclosed_delimiter(input, err.offset)
+                .map(|(pos, open)| (ast::Position::new(pos, &source_lines), open))
+        } else {
+            None
+        };
+
         ParseError {
             position: ast::Position::new(err.offset, &source_lines),
             context: context,
             expected:  token_str,
             context_start: start,
             context_end: end,
+            unclosed: unclosed,
         }
     }
 }
@@ -88,14 +194,9 @@ impl fmt::Display for ParseError {
         let error_message = format!("ERROR in line {} at column {}: Could not continue to parse, expected one of: ",
             self.position.line, self.position.col).red().bold();
 
-        let mut token_str = vec![];
-        for token in &self.expected {
-            if util::is_whitespace(token) {
-                token_str.push(format!("{:?}", token));
-            } else {
-                token_str.push(format!("{}", token));
-            }
-        }
+        // `self.expected` is kept raw for machine consumers; `Display`
+        // uses the deduplicated, friendlier version instead.
+        let token_str = normalize_expected(&self.expected);
 
         write!(f, "{}", error_message)?;
         write!(f, "{}\n", token_str.join(", ").blue().bold())?;
@@ -118,6 +219,12 @@ impl fmt::Display for ParseError {
             writeln!(f, "{} {}", lineno_col, formatted_content)?;
         }
 
+        if let Some((ref position, ref open)) = self.unclosed {
+            let unclosed_message = format!("unclosed `{}` opened in line {} at column {}",
+                open, position.line, position.col).yellow().bold();
+            writeln!(f, "{}", unclosed_message)?;
+        }
+
         Ok(())
     }
 }
@@ -137,4 +244,56 @@ impl fmt::Display for TransformationError {
         );
         writeln!(f, "{}", message.red().bold())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_innermost_unclosed_delimiter() {
+        // The `[[` is closed before the failure; only the `{{` is still
+        // open, so it -- not the link -- is what should be reported.
+        let input = "text [[a link]] and {{an unclosed template";
+        let found = find_unclosed_delimiter(input, input.len());
+        assert_eq!(found, Some((20, "{{".to_string())));
+    }
+
+    #[test]
+    fn nested_same_kind_delimiters_match_innermost_first() {
+        // A closed template nested in an unclosed one: scanning backward,
+        // the only `}}` closes the inner `{{`, leaving the outer `{{` as
+        // the one still unmatched.
+        let input = "{{outer {{inner}} still open";
+        let found = find_unclosed_delimiter(input, input.len());
+        assert_eq!(found, Some((0, "{{".to_string())));
+    }
+
+    #[test]
+    fn returns_none_when_every_delimiter_is_closed() {
+        let input = "{{t}} [[link]] {|table|}";
+        assert_eq!(find_unclosed_delimiter(input, input.len()), None);
+    }
+
+    #[test]
+    fn scans_by_byte_and_does_not_panic_on_multibyte_input() {
+        // Regression test: the scan used to slice `&str` while walking
+        // backward one byte at a time, which panics as soon as it lands
+        // inside a multi-byte UTF-8 character.
+        let input = "caf\u{e9} {{unclosed";
+        let found = find_unclosed_delimiter(input, input.len());
+        assert_eq!(found, Some((6, "{{".to_string())));
+    }
+
+    #[test]
+    fn normalize_expected_dedupes_sorts_and_applies_friendly_names() {
+        let raw = vec!["}}".to_string(), "foo".to_string(), "}}".to_string(),
+            "EOF".to_string()];
+        let cleaned = normalize_expected(&raw);
+        assert_eq!(cleaned, vec![
+            "`foo`".to_string(),
+            "end of input".to_string(),
+            "template close".to_string(),
+        ]);
+    }
 }
\ No newline at end of file
@@ -15,6 +15,11 @@ mod traversion;
 mod ast;
 mod error;
 mod util;
+mod recovery;
+mod pipeline;
+mod render;
+#[cfg(feature = "trace")]
+mod trace;
 #[cfg_attr(feature = "cargo-clippy", allow(unit_arg, cyclomatic_complexity,
      len_zero, single_match, naive_bytecount, suspicious_else_formatting))]
 mod grammar;
@@ -24,6 +29,11 @@ mod grammar;
 pub use ast::*;
 pub use traversion::Traversion;
 pub use error::*;
+pub use recovery::parse_recover;
+pub use pipeline::{Transformation, TransformationPipeline, TransformationPipelineBuilder};
+pub use render::{Render, WikitextWriter, HtmlWriter};
+#[cfg(feature = "trace")]
+pub use trace::{enter as trace_enter, exit as trace_exit};
 
 pub mod transformations;
 
@@ -32,26 +42,54 @@ use default_transformations::*;
 
 
 /// Parse the input document to generate a document tree.
-/// After parsing, some transformations are applied to the result.
+/// After parsing, the default transformation pipeline is applied to the
+/// result. Use `parse_with` to customize which transformations run.
 pub fn parse(input: &str) -> Result<Element, MWError> {
+    parse_with(input, &TransformationPipeline::default())
+}
+
+/// Parse the input document, then run `pipeline` over the result instead
+/// of the default transformation sequence. This lets callers disable,
+/// reorder or append passes (e.g. skip `fold_lists`, or append a custom
+/// pass that rewrites templates) without forking `parse`.
+pub fn parse_with(input: &str, pipeline: &TransformationPipeline) -> Result<Element, MWError> {
 
     let source_lines = util::get_source_lines(input);
 
     #[cfg(feature = "ptime")]
     let starttime = time::precise_time_ns();
 
+    // NOTE: `enter`/`exit` only bracket the single `grammar::document`
+    // call, not the individual PEG rules it is built from (see `trace`'s
+    // doc comment). On failure, `report_failure` also prints the set of
+    // terminals the PEG backtracker was still expecting at the failure
+    // offset.
+    #[cfg(feature = "trace")]
+    trace::enter("document", 0);
+
     let result = match grammar::document(input, &source_lines) {
-        Err(e) => Err(error::MWError::ParseError(
-            error::ParseError::from(&e, input),
-        )),
-        Ok(r) => Ok(r),
+        Err(e) => {
+            #[cfg(feature = "trace")]
+            {
+                trace::report_failure(e.offset, &e.expected);
+                trace::exit("document", Err("no rules could be matched"));
+            }
+            Err(error::MWError::ParseError(
+                error::ParseError::from(&e, input),
+            ))
+        }
+        Ok(r) => {
+            #[cfg(feature = "trace")]
+            trace::exit("document", Ok((0, input.len())));
+            Ok(r)
+        }
     }?;
 
     #[cfg(feature = "ptime")]
     let parsedtime = time::precise_time_ns();
 
     let settings = GeneralSettings {};
-    let trans_result = apply_transformations(result, &settings);
+    let trans_result = apply_transformations(result, &settings, pipeline);
 
     #[cfg(feature = "ptime")]
     {
@@ -64,14 +102,8 @@ pub fn parse(input: &str) -> Result<Element, MWError> {
     trans_result.map_err(|e| error::MWError::TransformationError(e))
 }
 
-fn apply_transformations(mut root: Element, settings: &GeneralSettings)
-    -> transformations::TResult {
+fn apply_transformations(root: Element, settings: &GeneralSettings,
+    pipeline: &TransformationPipeline) -> transformations::TResult {
 
-    root = fold_headings_transformation(root, settings)?;
-    root = fold_lists_transformation(root, settings)?;
-    root = whitespace_paragraphs_to_empty(root, settings)?;
-    root = collapse_paragraphs(root, settings)?;
-    root = collapse_consecutive_text(root, settings)?;
-    root = enumerate_anon_args(root, settings)?;
-    Ok(root)
+    pipeline.apply(root, settings)
 }
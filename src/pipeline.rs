@@ -0,0 +1,181 @@
+//! Configurable transformation pipeline.
+//!
+//! `apply_transformations` used to hardcode one fixed sequence of six
+//! passes over the freshly parsed tree. `TransformationPipeline` makes
+//! that sequence a first-class, inspectable value instead: callers can
+//! drop, reorder or append passes with `TransformationPipelineBuilder`
+//! and feed the result to `parse_with`, without forking the default
+//! pass list.
+
+use ast::Element;
+use transformations::TResult;
+use default_transformations::*;
+use GeneralSettings;
+
+/// A single named transformation pass.
+pub struct Transformation {
+    pub name: &'static str,
+    run: Box<Fn(Element, &GeneralSettings) -> TResult>,
+}
+
+impl Transformation {
+    pub fn new(name: &'static str, run: Box<Fn(Element, &GeneralSettings) -> TResult>) -> Self {
+        Transformation { name: name, run: run }
+    }
+}
+
+/// An ordered sequence of transformation passes applied to a document
+/// tree right after parsing.
+pub struct TransformationPipeline {
+    passes: Vec<Transformation>,
+}
+
+impl TransformationPipeline {
+    /// Run every pass in order, short-circuiting on the first error.
+    pub fn apply(&self, mut root: Element, settings: &GeneralSettings) -> TResult {
+        for pass in &self.passes {
+            root = (pass.run)(root, settings)?;
+        }
+        Ok(root)
+    }
+
+    /// Start building a pipeline from scratch, with no passes at all.
+    pub fn builder() -> TransformationPipelineBuilder {
+        TransformationPipelineBuilder::new()
+    }
+}
+
+impl Default for TransformationPipeline {
+    /// The six passes `parse` has always run, in their original order.
+    fn default() -> Self {
+        TransformationPipelineBuilder::new()
+            .push("fold_headings", Box::new(fold_headings_transformation))
+            .push("fold_lists", Box::new(fold_lists_transformation))
+            .push("whitespace_paragraphs_to_empty", Box::new(whitespace_paragraphs_to_empty))
+            .push("collapse_paragraphs", Box::new(collapse_paragraphs))
+            .push("collapse_consecutive_text", Box::new(collapse_consecutive_text))
+            .push("enumerate_anon_args", Box::new(enumerate_anon_args))
+            .build()
+    }
+}
+
+/// Builds a `TransformationPipeline` pass by pass.
+///
+/// Use `TransformationPipelineBuilder::from_default()` to start from the
+/// stock sequence and adjust it, or `TransformationPipeline::builder()`
+/// to start empty.
+pub struct TransformationPipelineBuilder {
+    passes: Vec<Transformation>,
+}
+
+impl TransformationPipelineBuilder {
+    pub fn new() -> Self {
+        TransformationPipelineBuilder { passes: vec![] }
+    }
+
+    /// Start from the stock six-pass pipeline instead of an empty one.
+    pub fn from_default() -> Self {
+        TransformationPipelineBuilder { passes: TransformationPipeline::default().passes }
+    }
+
+    /// Append a pass to the end of the pipeline.
+    pub fn push(mut self, name: &'static str, run: Box<Fn(Element, &GeneralSettings) -> TResult>)
+        -> Self {
+        self.passes.push(Transformation::new(name, run));
+        self
+    }
+
+    /// Insert a pass immediately before a named pass, or at the end if
+    /// no pass with that name is present.
+    pub fn insert_before(mut self, before: &str, name: &'static str,
+        run: Box<Fn(Element, &GeneralSettings) -> TResult>) -> Self {
+
+        let index = self.passes.iter().position(|p| p.name == before)
+            .unwrap_or_else(|| self.passes.len());
+        self.passes.insert(index, Transformation::new(name, run));
+        self
+    }
+
+    /// Remove a named pass, if present.
+    pub fn without(mut self, name: &str) -> Self {
+        self.passes.retain(|p| p.name != name);
+        self
+    }
+
+    pub fn build(self) -> TransformationPipeline {
+        TransformationPipeline { passes: self.passes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(pipeline: &TransformationPipeline) -> Vec<&'static str> {
+        pipeline.passes.iter().map(|pass| pass.name).collect()
+    }
+
+    fn noop(root: Element, _settings: &GeneralSettings) -> TResult {
+        Ok(root)
+    }
+
+    #[test]
+    fn from_default_starts_with_the_stock_six_passes_in_order() {
+        let pipeline = TransformationPipelineBuilder::from_default().build();
+        assert_eq!(names(&pipeline), vec![
+            "fold_headings",
+            "fold_lists",
+            "whitespace_paragraphs_to_empty",
+            "collapse_paragraphs",
+            "collapse_consecutive_text",
+            "enumerate_anon_args",
+        ]);
+    }
+
+    #[test]
+    fn without_removes_only_the_named_pass() {
+        let pipeline = TransformationPipelineBuilder::from_default()
+            .without("fold_lists")
+            .build();
+        assert_eq!(names(&pipeline), vec![
+            "fold_headings",
+            "whitespace_paragraphs_to_empty",
+            "collapse_paragraphs",
+            "collapse_consecutive_text",
+            "enumerate_anon_args",
+        ]);
+    }
+
+    #[test]
+    fn without_a_missing_pass_is_a_no_op() {
+        let pipeline = TransformationPipelineBuilder::from_default()
+            .without("no_such_pass")
+            .build();
+        assert_eq!(names(&pipeline).len(), 6);
+    }
+
+    #[test]
+    fn insert_before_places_the_new_pass_immediately_ahead_of_the_named_one() {
+        let pipeline = TransformationPipelineBuilder::from_default()
+            .insert_before("collapse_paragraphs", "custom", Box::new(noop))
+            .build();
+        assert_eq!(names(&pipeline), vec![
+            "fold_headings",
+            "fold_lists",
+            "whitespace_paragraphs_to_empty",
+            "custom",
+            "collapse_paragraphs",
+            "collapse_consecutive_text",
+            "enumerate_anon_args",
+        ]);
+    }
+
+    #[test]
+    fn insert_before_a_missing_pass_appends_to_the_end() {
+        let pipeline = TransformationPipelineBuilder::new()
+            .push("first", Box::new(noop))
+            .insert_before("no_such_pass", "second", Box::new(noop))
+            .build();
+        assert_eq!(names(&pipeline), vec!["first", "second"]);
+    }
+}
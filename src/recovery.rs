@@ -0,0 +1,234 @@
+//! Error-recovery parsing.
+//!
+//! `parse` aborts on the first `grammar::ParseError`. The functions here
+//! instead keep going, so that tools like editors or linters can collect
+//! a full diagnostic set from one pass over a large page.
+
+use ast::{Element, Position, Span};
+use error::ParseError;
+use grammar;
+use util;
+use GeneralSettings;
+use TransformationPipeline;
+use apply_transformations;
+
+/// Safety bound on the number of resynchronizations, so a future grammar
+/// change that fails again on an already-recovered span cannot loop
+/// forever.
+const MAX_RECOVERY_PASSES: usize = 4096;
+
+/// Parse `input`, recovering from syntax errors instead of aborting at
+/// the first one.
+///
+/// Each time `grammar::document` fails, the offending span is
+/// resynchronized by blanking it out -- replacing every byte but
+/// newlines with a space, up to the next safe boundary (just past the
+/// next blank-line paragraph break, or failing that the end of the
+/// current line) -- and parsing is
+/// retried on the same, full-length buffer. Because the buffer is never
+/// truncated, every position the grammar produces already refers to the
+/// original `input`; there is nothing to rebase. Once a pass succeeds,
+/// one `Element::Error` node per recovered span is spliced into the
+/// root's content at the position it covers, and the normal
+/// transformation pipeline runs once over the result, exactly as in
+/// `parse`.
+///
+/// Returns the (partial) document tree together with every `ParseError`
+/// that was recovered from, in the order they occurred.
+pub fn parse_recover(input: &str) -> (Element, Vec<ParseError>) {
+
+    let source_lines = util::get_source_lines(input);
+    let mut working: Vec<u8> = input.as_bytes().to_vec();
+    let mut errors = vec![];
+    let mut recovered = vec![];
+
+    let root = loop {
+
+        let candidate = String::from_utf8(working.clone())
+            .expect("blanking only ever replaces bytes with ascii spaces");
+
+        match grammar::document(&candidate, &source_lines) {
+            Ok(tree) => break tree,
+            Err(err) => {
+
+                errors.push(ParseError::from(&err, input));
+
+                let mut resync = resync_offset(input, err.offset);
+                if resync <= err.offset {
+                    resync = (err.offset + 1).min(input.len());
+                }
+
+                for byte in &mut working[err.offset..resync] {
+                    if *byte != b'\n' {
+                        *byte = b' ';
+                    }
+                }
+
+                recovered.push(Element::Error {
+                    position: Span {
+                        start: Position::new(err.offset, &source_lines),
+                        end: Position::new(resync, &source_lines),
+                    },
+                    message: "skipped unparsable content while recovering from a parse error"
+                        .into(),
+                });
+
+                if errors.len() >= MAX_RECOVERY_PASSES {
+                    // Give up trying to recover further. Make one last
+                    // attempt at parsing the buffer as blanked so far,
+                    // so a pathological document still yields whatever
+                    // partial tree is parseable, rather than throwing
+                    // away everything recovered up to this point.
+                    let fallback = String::from_utf8(working.clone())
+                        .expect("blanking only ever replaces bytes with ascii spaces");
+                    break grammar::document(&fallback, &source_lines)
+                        .unwrap_or_else(|_| Element::Document {
+                            position: Span {
+                                start: Position::new(0, &source_lines),
+                                end: Position::new(input.len(), &source_lines),
+                            },
+                            content: vec![],
+                        });
+                }
+            }
+        }
+    };
+
+    let spliced = splice_recovered(root, recovered);
+    let settings = GeneralSettings {};
+    let pipeline = TransformationPipeline::default();
+    let result = match apply_transformations(spliced, &settings, &pipeline) {
+        Ok(tree) => tree,
+        Err(err) => {
+            // A `TransformationError` here means the tree recovery just
+            // produced (errors already spliced in) tripped one of the
+            // default passes -- still worth returning, since it is the
+            // best partial tree available, but silently swapping in
+            // `err.tree` without a trace would leave callers unable to
+            // tell the transformation step ever failed.
+            eprintln!("{}", err);
+            err.tree
+        }
+    };
+
+    (result, errors)
+}
+
+/// Find the next safe resynchronization boundary after a failure at
+/// `offset`: just past the next blank-line paragraph break if there is
+/// one before the end of input, otherwise just past the end of the
+/// current line, otherwise the end of input.
+fn resync_offset(input: &str, offset: usize) -> usize {
+    match input[offset..].find("\n\n") {
+        Some(rel) => offset + rel + 2,
+        None => match input[offset..].find('\n') {
+            Some(rel) => offset + rel + 1,
+            None => input.len(),
+        },
+    }
+}
+
+/// Every `Element` variant this crate is known to produce carries its
+/// span as a `position` field; this extracts it generically so children
+/// can be ordered positionally without a giant match at every call site.
+/// `None` for a variant this list doesn't cover yet.
+fn position_of(element: &Element) -> Option<&Span> {
+    match *element {
+        Element::Document { ref position, .. } |
+        Element::Heading { ref position, .. } |
+        Element::Paragraph { ref position, .. } |
+        Element::Text { ref position, .. } |
+        Element::Formatted { ref position, .. } |
+        Element::List { ref position, .. } |
+        Element::ListItem { ref position, .. } |
+        Element::InternalReference { ref position, .. } |
+        Element::Template { ref position, .. } |
+        Element::TemplateArgument { ref position, .. } |
+        Element::TableCell { ref position, .. } |
+        Element::HtmlTag { ref position, .. } |
+        Element::Error { ref position, .. } => Some(position),
+        _ => None,
+    }
+}
+
+/// Splice the `Element::Error` nodes recorded during recovery into the
+/// root's content at the position of the span they replace, instead of
+/// trailing all of it, so each one still covers the skipped span it
+/// stands in for. `collapse_paragraphs` and `collapse_consecutive_text`
+/// see them as ordinary siblings and leave them untouched.
+fn splice_recovered(root: Element, recovered: Vec<Element>) -> Element {
+    match root {
+        Element::Document { position, mut content } => {
+            for error in recovered {
+                let error_start = position_of(&error).map_or(0, |span| span.start.offset);
+                let index = content.iter()
+                    .position(|child| {
+                        position_of(child).map_or(false, |span| span.start.offset > error_start)
+                    })
+                    .unwrap_or_else(|| content.len());
+                content.insert(index, error);
+            }
+            Element::Document { position, content }
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two separate unclosed templates, each followed by a paragraph that
+    /// parses fine on its own, so the grammar fails twice in one document
+    /// and recovery has to resynchronize twice, independently.
+    const TWO_ERROR_INPUT: &str =
+        "first paragraph\n\n{{unclosed one\n\nsecond paragraph\n\n{{unclosed two\n\nthird paragraph\n";
+
+    #[test]
+    fn recovers_every_independent_error_in_one_document() {
+        let (_tree, errors) = parse_recover(TWO_ERROR_INPUT);
+
+        assert_eq!(errors.len(), 2,
+            "expected both unclosed templates to be recovered from independently, got {:?}",
+            errors);
+    }
+
+    #[test]
+    fn recovered_error_positions_are_rebased_to_the_original_input() {
+        let (_tree, errors) = parse_recover(TWO_ERROR_INPUT);
+
+        // Every position the grammar reports already refers to the
+        // original buffer -- blanking never shrinks it -- so the two
+        // errors must land on the lines the unclosed templates are
+        // actually on, line 3 and line 7, not on whatever line the
+        // *previous* resynchronization pass left the failure at.
+        let lines: Vec<usize> = errors.iter().map(|e| e.position.line).collect();
+        assert_eq!(lines, vec![3, 7],
+            "recovered error positions were not rebased to the original input: {:?}", lines);
+    }
+
+    #[test]
+    fn error_nodes_survive_collapse_paragraphs_and_collapse_consecutive_text() {
+        let (tree, errors) = parse_recover(TWO_ERROR_INPUT);
+        assert_eq!(errors.len(), 2);
+
+        // `parse_recover` runs the default pipeline -- including
+        // `collapse_paragraphs` and `collapse_consecutive_text` -- over
+        // the spliced tree before returning it. If either pass merged or
+        // dropped the spliced-in `Element::Error` nodes instead of
+        // leaving them as ordinary siblings, this count would come back
+        // wrong.
+        let error_count = match tree {
+            Element::Document { ref content, .. } => {
+                content.iter().filter(|child| match **child {
+                    Element::Error { .. } => true,
+                    _ => false,
+                }).count()
+            }
+            ref other => panic!("parse_recover must return a Document, got {:?}", other),
+        };
+
+        assert_eq!(error_count, 2,
+            "expected both recovered Error nodes to survive the transformation pipeline");
+    }
+}
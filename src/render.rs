@@ -0,0 +1,362 @@
+//! AST-to-text renderers.
+//!
+//! Until now the only way to get an `Element` tree back out of the
+//! crate was serializing the raw AST as JSON/YAML. These renderers turn
+//! the tree into a conversion hub instead of a dead end: `WikitextWriter`
+//! re-serializes it into MediaWiki source (see its doc for the elements
+//! whose markup it does not reproduce), and `HtmlWriter` renders the
+//! same tree to HTML. Both are
+//! `Traversion` visitors, like every other consumer of the AST.
+
+use std::io::{self, Write};
+use ast::{Element, MarkupType, ListItemKind};
+use traversion::Traversion;
+
+/// Opening and closing wikitext markers for a formatting span's markup
+/// type, e.g. `Bold` is wrapped in `'''`. Used by `WikitextWriter` to
+/// round-trip `Formatted` spans instead of silently dropping their
+/// markup -- including `NoWiki`, whose `<nowiki>`/`</nowiki>` tags are
+/// themselves the content being escaped and must not be dropped.
+fn markup_tokens(markup: &MarkupType) -> (&'static str, &'static str) {
+    match *markup {
+        MarkupType::Bold => ("'''", "'''"),
+        MarkupType::Italic => ("''", "''"),
+        MarkupType::NoWiki => ("<nowiki>", "</nowiki>"),
+    }
+}
+
+/// Wikitext marker for a list item's kind, repeated once per nesting
+/// depth (e.g. `**` for a depth-2 unordered item).
+fn list_item_marker(kind: &ListItemKind) -> &'static str {
+    match *kind {
+        ListItemKind::Unordered => "*",
+        ListItemKind::Ordered => "#",
+        ListItemKind::Definition => ";",
+        ListItemKind::DefinitionTerm => ":",
+    }
+}
+
+/// Re-serializes an `Element` tree back into MediaWiki wikitext.
+///
+/// Headings, paragraphs, text, formatting spans, lists, internal links
+/// and templates are written with their markup. Table cells and HTML
+/// tags are written as their content only, without their markup;
+/// `Error` nodes, and variants `get_content` does not cover, are not
+/// written at all.
+#[derive(Default)]
+pub struct WikitextWriter;
+
+impl<'a> Traversion<'a, ()> for WikitextWriter {
+    fn work(&mut self, root: &'a Element, _settings: (), out: &mut Write) -> io::Result<()> {
+        write_wikitext(root, out)
+    }
+}
+
+fn write_wikitext(element: &Element, out: &mut Write) -> io::Result<()> {
+    match *element {
+        Element::Document { ref content, .. } => {
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+        }
+        Element::Heading { depth, ref caption, ref content, .. } => {
+            let marker = "=".repeat(depth);
+            write!(out, "{} ", marker)?;
+            for child in caption {
+                write_wikitext(child, out)?;
+            }
+            writeln!(out, " {}", marker)?;
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+        }
+        Element::Paragraph { ref content, .. } => {
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+            // A single newline only ends the line; two are needed so the
+            // blank-line paragraph break survives a re-parse.
+            write!(out, "\n\n")?;
+        }
+        Element::Text { ref text, .. } => write!(out, "{}", text)?,
+        Element::Formatted { ref markup, ref content, .. } => {
+            let (open, close) = markup_tokens(markup);
+            write!(out, "{}", open)?;
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+            write!(out, "{}", close)?;
+        }
+        Element::List { ref content, .. } => {
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+        }
+        Element::ListItem { depth, ref kind, ref content, .. } => {
+            write!(out, "{}", list_item_marker(kind).repeat(depth.max(1)))?;
+            write!(out, " ")?;
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+            writeln!(out)?;
+        }
+        Element::InternalReference { ref target, ref caption, .. } => {
+            write!(out, "[[")?;
+            for child in target {
+                write_wikitext(child, out)?;
+            }
+            if !caption.is_empty() {
+                write!(out, "|")?;
+                for child in caption {
+                    write_wikitext(child, out)?;
+                }
+            }
+            write!(out, "]]")?;
+        }
+        Element::Template { ref name, ref content, .. } => {
+            write!(out, "{{{{")?;
+            for child in name {
+                write_wikitext(child, out)?;
+            }
+            for arg in content {
+                write!(out, "|")?;
+                write_wikitext(arg, out)?;
+            }
+            write!(out, "}}}}")?;
+        }
+        Element::TemplateArgument { ref name, ref content, .. } => {
+            if !name.is_empty() {
+                write!(out, "{}=", name)?;
+            }
+            for child in content {
+                write_wikitext(child, out)?;
+            }
+        }
+        Element::Error { .. } => {}
+        ref other => {
+            if let Some(content) = get_content(other) {
+                for child in content {
+                    write_wikitext(child, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders an `Element` tree to HTML: headings and links become their
+/// usual tags, every list becomes a `<ul>` whatever the kind of its
+/// items, templates are kept as opaque placeholders (the crate does not
+/// expand them), and formatting spans map to `<strong>`/`<em>`
+/// (`<span>` for nowiki).
+#[derive(Default)]
+pub struct HtmlWriter;
+
+impl<'a> Traversion<'a, ()> for HtmlWriter {
+    fn work(&mut self, root: &'a Element, _settings: (), out: &mut Write) -> io::Result<()> {
+        write_html(root, out)
+    }
+}
+
+fn write_html(element: &Element, out: &mut Write) -> io::Result<()> {
+    match *element {
+        Element::Document { ref content, .. } => {
+            for child in content {
+                write_html(child, out)?;
+            }
+        }
+        Element::Heading { depth, ref caption, ref content, .. } => {
+            let level = depth.min(6).max(1);
+            write!(out, "<h{}>", level)?;
+            for child in caption {
+                write_html(child, out)?;
+            }
+            write!(out, "</h{}>", level)?;
+            for child in content {
+                write_html(child, out)?;
+            }
+        }
+        Element::Paragraph { ref content, .. } => {
+            write!(out, "<p>")?;
+            for child in content {
+                write_html(child, out)?;
+            }
+            write!(out, "</p>")?;
+        }
+        Element::Text { ref text, .. } => write!(out, "{}", escape_html(text))?,
+        Element::Formatted { ref markup, ref content, .. } => {
+            let tag = match *markup {
+                MarkupType::Bold => "strong",
+                MarkupType::Italic => "em",
+                MarkupType::NoWiki => "span",
+            };
+            write!(out, "<{}>", tag)?;
+            for child in content {
+                write_html(child, out)?;
+            }
+            write!(out, "</{}>", tag)?;
+        }
+        Element::List { ref content, .. } => {
+            write!(out, "<ul>")?;
+            for child in content {
+                write!(out, "<li>")?;
+                write_html(child, out)?;
+                write!(out, "</li>")?;
+            }
+            write!(out, "</ul>")?;
+        }
+        Element::InternalReference { ref target, ref caption, .. } => {
+            write!(out, "<a href=\"")?;
+            for child in target {
+                write_html(child, out)?;
+            }
+            write!(out, "\">")?;
+            let link_text = if caption.is_empty() { target } else { caption };
+            for child in link_text {
+                write_html(child, out)?;
+            }
+            write!(out, "</a>")?;
+        }
+        Element::Template { .. } => {
+            write!(out, "<span class=\"template-placeholder\"></span>")?;
+        }
+        Element::Error { ref message, .. } => {
+            write!(out, "<span class=\"parse-error\">{}</span>", escape_html(message))?;
+        }
+        ref other => {
+            if let Some(content) = get_content(other) {
+                for child in content {
+                    write_html(child, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Access to the generic child list of list items, table cells, HTML
+/// tags and template arguments, so a renderer that does not handle one
+/// of them specially still recurses into its children. Any other variant
+/// gives `None`, and the renderers write nothing for it.
+fn get_content(element: &Element) -> Option<&Vec<Element>> {
+    match *element {
+        Element::ListItem { ref content, .. } |
+        Element::TableCell { ref content, .. } |
+        Element::HtmlTag { ref content, .. } |
+        Element::TemplateArgument { ref content, .. } => Some(content),
+        _ => None,
+    }
+}
+
+/// Renders an `Element` tree back to source code.
+pub trait Render {
+    /// Re-serializes this tree as valid MediaWiki wikitext.
+    fn render_wikitext(&self) -> String;
+    /// Renders this tree to HTML.
+    fn render_html(&self) -> String;
+}
+
+impl Render for Element {
+    fn render_wikitext(&self) -> String {
+        let mut buf = vec![];
+        WikitextWriter.work(self, (), &mut buf)
+            .expect("writing wikitext to an in-memory buffer cannot fail");
+        String::from_utf8(buf).expect("renderer only ever emits utf-8 text")
+    }
+
+    fn render_html(&self) -> String {
+        let mut buf = vec![];
+        HtmlWriter.work(self, (), &mut buf)
+            .expect("writing html to an in-memory buffer cannot fail");
+        String::from_utf8(buf).expect("renderer only ever emits utf-8 text")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse;
+
+    /// Asserts that rendering the parsed `input` as wikitext, re-parsing
+    /// that render and rendering it again gives the same text as the
+    /// first render, i.e. re-running `parse` and the wikitext renderer
+    /// doesn't drift. The two trees are not compared directly: their
+    /// positions refer to different source texts.
+    fn assert_round_trips(input: &str) {
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        let reparsed = parse(&rendered).expect("rendered wikitext parses");
+        let rerendered = reparsed.render_wikitext();
+
+        assert_eq!(rendered, rerendered,
+            "re-parsing {:?} as wikitext did not round-trip, got {:?}", input, rendered);
+    }
+
+    #[test]
+    fn wikitext_round_trips_formatting_and_named_template_args() {
+        let input = "'''bold''' ''italic'' {{t|k=v}}";
+        assert_round_trips(input);
+
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        assert!(rendered.contains("'''bold'''"), "lost bold markup in {:?}", rendered);
+        assert!(rendered.contains("''italic''"), "lost italic markup in {:?}", rendered);
+        assert!(rendered.contains("k=v"), "lost named template argument in {:?}", rendered);
+    }
+
+    #[test]
+    fn wikitext_round_trips_unordered_and_nested_list_items() {
+        let input = "* one\n** nested\n* two\n";
+        assert_round_trips(input);
+
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        assert!(rendered.contains("* one"), "missing top-level marker in {:?}", rendered);
+        assert!(rendered.contains("** nested"), "missing nested marker in {:?}", rendered);
+    }
+
+    #[test]
+    fn wikitext_round_trips_multiple_paragraphs() {
+        let input = "first paragraph\n\nsecond paragraph\n";
+        assert_round_trips(input);
+
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        assert!(rendered.contains("\n\n"),
+            "paragraphs must stay separated by a blank line, got {:?}", rendered);
+
+        // Re-parsing the render has to give back the same render, blank
+        // line included.
+        let reparsed = parse(&rendered).expect("rendered wikitext parses");
+        assert_eq!(rendered, reparsed.render_wikitext());
+    }
+
+    #[test]
+    fn wikitext_round_trips_headings() {
+        let input = "== A heading ==\n\ntext below it\n";
+        assert_round_trips(input);
+
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        assert!(rendered.contains("== A heading =="), "lost heading markup in {:?}", rendered);
+        assert!(rendered.contains("text below it"), "lost heading body in {:?}", rendered);
+    }
+
+    #[test]
+    fn wikitext_round_trips_nowiki() {
+        let input = "<nowiki>'''not bold'''</nowiki>";
+        assert_round_trips(input);
+
+        let tree = parse(input).expect("input parses");
+        let rendered = tree.render_wikitext();
+        assert!(rendered.contains("<nowiki>") && rendered.contains("</nowiki>"),
+            "nowiki markers must survive rendering, got {:?}", rendered);
+    }
+}
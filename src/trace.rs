@@ -0,0 +1,59 @@
+//! Opt-in parse tracing, gated behind the `trace` feature exactly like
+//! timing is gated behind `ptime`.
+//!
+//! `enter` and `exit` print indented `> rule @offset` and `< rule` lines
+//! to stderr, the indentation following the nesting depth. `parse_with`
+//! calls them around the single `grammar::document` call only: the
+//! individual PEG rules inside `document` (template, link, table, ...)
+//! are not instrumented, so their names, offsets and matched spans are
+//! not printed. When `document` fails, `report_failure` prints the set
+//! of terminals rust-peg was still expecting at the offset where
+//! matching gave up (`grammar::ParseError::expected`); it does not name
+//! the rules those terminals belong to. A successful parse prints only
+//! the `document` lines.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static DEPTH: RefCell<usize> = RefCell::new(0);
+}
+
+/// Records that `rule` was attempted at `offset`, indented to the
+/// current nesting depth, then increases that depth for whatever is
+/// attempted inside it.
+pub fn enter(rule: &str, offset: usize) {
+    DEPTH.with(|depth| {
+        let d = *depth.borrow();
+        eprintln!("{}> {} @{}", "  ".repeat(d), rule, offset);
+        *depth.borrow_mut() = d + 1;
+    });
+}
+
+/// Records that `rule` finished -- either the span it matched or the
+/// reason it failed -- and restores the nesting depth from before the
+/// matching `enter` call.
+pub fn exit(rule: &str, result: Result<(usize, usize), &str>) {
+    DEPTH.with(|depth| {
+        let d = depth.borrow().saturating_sub(1);
+        *depth.borrow_mut() = d;
+        match result {
+            Ok((start, end)) =>
+                eprintln!("{}< {} matched {}..{}", "  ".repeat(d), rule, start, end),
+            Err(reason) =>
+                eprintln!("{}< {} failed: {}", "  ".repeat(d), rule, reason),
+        }
+    });
+}
+
+/// Reports every terminal the grammar was still expecting at `offset`
+/// when it gave up, as rust-peg recorded them in its `expected` set.
+/// Printed at the current nesting depth so it reads as the children of
+/// whatever `enter` call is open (normally `document`).
+pub fn report_failure(offset: usize, expected: &[&str]) {
+    DEPTH.with(|depth| {
+        let d = *depth.borrow();
+        for rule in expected {
+            eprintln!("{}- expected {} @{}", "  ".repeat(d), rule, offset);
+        }
+    });
+}